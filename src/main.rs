@@ -7,26 +7,63 @@ use std::io;
 use anyhow::{anyhow, Result};
 
 pub mod process;
+pub mod store;
 pub mod types;
 
-static ARG_MSG: &str = "Expected one positional argument (path to CSV file to process)";
+static ARG_MSG: &str =
+	"Expected a path to a CSV file to process, with optional `--parallel <N>` and `--strict` flags";
 
-/// Parse Arg
+/// Parsed command line arguments.
+struct Args {
+	filepath: String,
+	/// Number of worker threads to shard processing across, if parallel mode was requested.
+	workers: Option<usize>,
+	/// Reject (rather than skip) malformed rows, aborting with an aggregated report.
+	strict: bool,
+}
+
+/// Parse Args
 ///
-/// Parse a single positional argument, returning an error if anything other than that is present.
-/// (Skipping a dependency on `Clap` or equivalent given how simple this is).
-fn parse_arg() -> Result<String> {
-	let mut args = env::args();
-	if args.len() > 2 {
-		return Err(anyhow!(ARG_MSG)); // Reject any unexpected args, just to be sure
+/// Parse the positional CSV path plus an optional `--parallel <N>` (or `-j <N>`) flag selecting
+/// parallel processing with `N` workers and an optional `--strict` flag selecting strict
+/// validation. (Skipping a dependency on `Clap` or equivalent given how simple this is).
+fn parse_args() -> Result<Args> {
+	let mut filepath = None;
+	let mut workers = None;
+	let mut strict = false;
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--parallel" | "-j" => {
+				let n = args
+					.next()
+					.ok_or_else(|| anyhow!("`{}` requires a worker count", arg))?;
+				workers = Some(n.parse().map_err(|_| anyhow!("invalid worker count '{}'", n))?);
+			}
+			"--strict" => strict = true,
+			_ if filepath.is_none() => filepath = Some(arg),
+			_ => return Err(anyhow!(ARG_MSG)), // Reject any unexpected args, just to be sure
+		}
 	}
-	args.nth(1).ok_or(anyhow!(ARG_MSG))
+	Ok(Args {
+		filepath: filepath.ok_or_else(|| anyhow!(ARG_MSG))?,
+		workers,
+		strict,
+	})
 }
 
 fn main() -> Result<()> {
 	env_logger::init();
-	let filepath = parse_arg()?;
-	let mut input = File::open(filepath)?;
+	let args = parse_args()?;
+	let mode = if args.strict {
+		process::Mode::Strict
+	} else {
+		process::Mode::Lenient
+	};
+	let mut input = File::open(args.filepath)?;
 	let mut output = io::stdout();
-	process::run(&mut input, &mut output)
+	match args.workers {
+		Some(workers) => process::run_parallel(&mut input, &mut output, workers, mode),
+		None => process::run_with_mode(&mut input, &mut output, mode),
+	}
 }
@@ -2,58 +2,247 @@
 
 use std::io::{BufReader, BufWriter, Read, Write};
 
-use anyhow::Result;
+use std::sync::mpsc::sync_channel;
+use std::thread;
+
+use anyhow::{anyhow, Result};
 use csv::Trim;
 
-use crate::types::{Account, Accounts, CachedTx, ClientId, Transaction, TransactionType, TxCache};
+use crate::store::{MemStore, Store};
+use crate::types::{
+	Account, Amount, CachedTx, ClientId, LedgerError, Transaction, TransactionType, TxState,
+};
 
 /// Run
 ///
 /// Read and process all transactions from `input` (trait bound `std::io::Read`) and write
-/// the results to `output` (trait bound `std::io::Write`).
+/// the results to `output` (trait bound `std::io::Write`). Uses the default in-memory
+/// [`MemStore`]; callers needing an out-of-core backend can use [`run_with`].
 pub fn run<R: Read, W: Write>(input: &mut R, output: &mut W) -> Result<()> {
-	let mut accounts = Accounts::new();
-	let mut tx_cache = TxCache::new();
-	process_transactions(input, &mut accounts, &mut tx_cache)?;
-	write_accounts(output, accounts)
+	run_with(input, output, MemStore::new())
+}
+
+/// Run With
+///
+/// As [`run`], but processes transactions against a caller-supplied [`Store`], so very large
+/// transaction logs can be backed by a disk/embedded-KV store rather than in-memory `HashMap`s.
+pub fn run_with<R: Read, W: Write, S: Store>(
+	input: &mut R,
+	output: &mut W,
+	mut store: S,
+) -> Result<()> {
+	process_transactions(input, &mut store, Mode::Lenient)?;
+	write_accounts(output, &store, EXISTENTIAL_DEPOSIT)
+}
+
+/// Run With Mode
+///
+/// As [`run`], but with a configurable validation [`Mode`]. In [`Mode::Strict`] every anomaly
+/// (unknown client, missing tx reference, already-disputed transaction, etc.) is accumulated and,
+/// if any rows were rejected, the run aborts with an aggregated error instead of writing output.
+/// [`Mode::Lenient`] preserves the default skip-and-continue behaviour.
+pub fn run_with_mode<R: Read, W: Write>(
+	input: &mut R,
+	output: &mut W,
+	mode: Mode,
+) -> Result<()> {
+	let mut store = MemStore::new();
+	let rejections = process_transactions(input, &mut store, mode)?;
+	if !rejections.is_empty() {
+		return Err(aggregated_rejection_error(&rejections));
+	}
+	write_accounts(output, &store, EXISTENTIAL_DEPOSIT)
+}
+
+/// Validation strictness applied while processing transactions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+	/// Log and skip anomalies, continuing with the remaining rows (the default).
+	Lenient,
+	/// Accumulate every rejected row so the run can abort with an aggregated report.
+	Strict,
+}
+
+/// A rejected input row: its position in the file, the offending transaction and the reason.
+#[derive(Debug)]
+pub struct Rejection {
+	pub row: usize,
+	pub transaction: Transaction,
+	pub reason: LedgerError,
 }
 
 /// Process Transactions
 ///
 /// Process all transactions from a reader which provides transactions in a CSV format, adding
-/// relevant data to client accounts (`accounts`) and using a transaction cache (`tx_cache`) to
-/// cache transactions relevant for disputes.
-fn process_transactions<R: Read>(
+/// relevant data to client accounts and caching transactions relevant for disputes, both held in
+/// the supplied [`Store`]. Rows rejected under [`Mode::Strict`] are returned for reporting; under
+/// [`Mode::Lenient`] the returned vector is always empty.
+fn process_transactions<R: Read, S: Store>(
 	input: &mut R,
-	accounts: &mut Accounts,
-	tx_cache: &mut TxCache,
-) -> Result<()> {
+	store: &mut S,
+	mode: Mode,
+) -> Result<Vec<Rejection>> {
 	let buffered = BufReader::new(input);
 	let mut rdr = csv::ReaderBuilder::new()
 		.trim(Trim::All)
 		.from_reader(buffered);
 
-	for result in rdr.deserialize() {
+	let mut rejections = Vec::new();
+	for (i, result) in rdr.deserialize().enumerate() {
 		let transaction: Transaction = result?;
 		log::debug!("{:?}", transaction);
+		if let Err(reason) = apply_transaction(store, &transaction) {
+			// +2: row 1 is the header, and `enumerate` is zero-based.
+			record_rejection(mode, &mut rejections, i + 2, transaction, reason);
+		}
+	}
+
+	Ok(rejections)
+}
+
+/// Record Rejection
+///
+/// Under [`Mode::Lenient`] a rejected row is logged and discarded; under [`Mode::Strict`] it is
+/// accumulated so callers can audit everything that was dropped.
+fn record_rejection(
+	mode: Mode,
+	rejections: &mut Vec<Rejection>,
+	row: usize,
+	transaction: Transaction,
+	reason: LedgerError,
+) {
+	match mode {
+		Mode::Lenient => log::debug!("row {}: {}", row, reason),
+		Mode::Strict => rejections.push(Rejection {
+			row,
+			transaction,
+			reason,
+		}),
+	}
+}
 
-		// NB this results in getting the account twice in some cases. Not the most
-		// efficient, but it's easily optimized:
-		if let Some(account) = accounts.get(&transaction.client) {
-			if account.locked {
-				continue;
+/// Aggregated Rejection Error
+///
+/// Build a single error summarising every row rejected in strict mode.
+fn aggregated_rejection_error(rejections: &[Rejection]) -> anyhow::Error {
+	let mut msg = format!("{} row(s) rejected in strict mode:", rejections.len());
+	for rejection in rejections {
+		msg.push_str(&format!(
+			"\n  row {} (client {}, tx {}): {}",
+			rejection.row,
+			rejection.transaction.client,
+			rejection.transaction.tx,
+			rejection.reason
+		));
+	}
+	anyhow!(msg)
+}
+
+/// Run Parallel
+///
+/// As [`run`], but shards the transaction stream across `workers` worker threads. Every handler
+/// only ever touches a single client's account and that client's transactions, so the stream can
+/// be partitioned by `client % workers` without changing results for input whose transaction IDs
+/// are unique: each transaction is routed over a bounded channel to a worker that owns its own
+/// [`MemStore`] partition, which preserves each client's ordering within its shard. The per-shard
+/// account maps are merged before writing, and the validation [`Mode`] is applied independently
+/// within each shard. Duplicate transaction IDs are only detected within a shard, so a deposit
+/// reusing the ID of a transaction routed to another shard is accepted here, where [`run`]
+/// rejects it.
+pub fn run_parallel<R: Read, W: Write>(
+	input: &mut R,
+	output: &mut W,
+	workers: usize,
+	mode: Mode,
+) -> Result<()> {
+	let (merged, rejections) = process_parallel(input, workers, mode)?;
+	if !rejections.is_empty() {
+		return Err(aggregated_rejection_error(&rejections));
+	}
+	write_accounts(output, &merged, EXISTENTIAL_DEPOSIT)
+}
+
+/// Process Parallel
+///
+/// Spawn `workers` worker threads, route each deserialized transaction (tagged with its file row)
+/// to `client % workers` over a bounded channel, then join the workers and merge their account
+/// partitions and rejection records.
+fn process_parallel<R: Read>(
+	input: &mut R,
+	workers: usize,
+	mode: Mode,
+) -> Result<(MemStore, Vec<Rejection>)> {
+	let workers = workers.max(1);
+
+	let mut senders = Vec::with_capacity(workers);
+	let mut handles = Vec::with_capacity(workers);
+	for _ in 0..workers {
+		let (tx, rx) = sync_channel::<(usize, Transaction)>(1024);
+		senders.push(tx);
+		handles.push(thread::spawn(move || {
+			let mut store = MemStore::new();
+			let mut rejections = Vec::new();
+			for (row, transaction) in rx {
+				log::debug!("{:?}", transaction);
+				if let Err(reason) = apply_transaction(&mut store, &transaction) {
+					record_rejection(mode, &mut rejections, row, transaction, reason);
+				}
 			}
-		}
+			(store, rejections)
+		}));
+	}
+
+	let buffered = BufReader::new(input);
+	let mut rdr = csv::ReaderBuilder::new()
+		.trim(Trim::All)
+		.from_reader(buffered);
+	for (i, result) in rdr.deserialize().enumerate() {
+		let transaction: Transaction = result?;
+		let shard = (transaction.client as usize) % workers;
+		// +2: row 1 is the header, and `enumerate` is zero-based.
+		senders[shard]
+			.send((i + 2, transaction))
+			// A send only fails if the worker has hung up, which means it panicked.
+			.map_err(|_| anyhow!("worker thread {} terminated unexpectedly", shard))?;
+	}
+	drop(senders);
+
+	let mut merged = MemStore::new();
+	let mut rejections = Vec::new();
+	for handle in handles {
+		let (store, shard_rejections) =
+			handle.join().map_err(|_| anyhow!("worker thread panicked"))?;
+		merged.merge(store);
+		rejections.extend(shard_rejections);
+	}
+	// Keep the aggregated report deterministic regardless of shard completion order.
+	rejections.sort_by_key(|rejection| rejection.row);
+	Ok((merged, rejections))
+}
+
+/// Apply Transaction
+///
+/// Dispatch a single transaction to the relevant handler, short-circuiting if the client's
+/// account is frozen. Returns any [`LedgerError`] so the caller can log it (lenient) or record it
+/// (strict).
+fn apply_transaction<S: Store>(
+	store: &mut S,
+	transaction: &Transaction,
+) -> Result<(), LedgerError> {
+	// NB this results in getting the account twice in some cases. Not the most
+	// efficient, but it's easily optimized:
+	let client = transaction.client;
+	if store.get_account(client).map_or(false, |a| a.locked) {
+		Err(LedgerError::FrozenAccount(client))
+	} else {
 		match transaction.r#type {
-			TransactionType::Deposit => handle_deposit(accounts, tx_cache, transaction),
-			TransactionType::Withdrawal => handle_withdrawal(accounts, transaction),
-			TransactionType::Dispute => handle_dispute(accounts, tx_cache, transaction),
-			TransactionType::Resolve => handle_resolve(accounts, tx_cache, transaction),
-			TransactionType::Chargeback => handle_chargeback(accounts, tx_cache, transaction),
+			TransactionType::Deposit => handle_deposit(store, transaction),
+			TransactionType::Withdrawal => handle_withdrawal(store, transaction),
+			TransactionType::Dispute => handle_dispute(store, transaction),
+			TransactionType::Resolve => handle_resolve(store, transaction),
+			TransactionType::Chargeback => handle_chargeback(store, transaction),
 		}
 	}
-
-	Ok(())
 }
 
 /// Handle Deposit
@@ -61,27 +250,31 @@ fn process_transactions<R: Read>(
 /// If no client account exists yet, we create one (NB this is the only occasion where we create
 /// new client accounts). We then increase the total funds by the transaction amount, implicitly
 /// increasing the available amount and insert the new transaction into the transaction cache. If
-/// the transaction ID already exists we ignore it.
-fn handle_deposit(accounts: &mut Accounts, tx_cache: &mut TxCache, transaction: Transaction) {
-	if tx_cache.contains_key(&transaction.tx) {
+/// the transaction ID already exists we reject it as a duplicate.
+fn handle_deposit<S: Store>(store: &mut S, transaction: &Transaction) -> Result<(), LedgerError> {
+	if store.get_tx(transaction.tx).is_some() {
 		// Transactions are globally unique, but the spec didn't say we can rely on not being passed
 		// the same transaction twice.
-		return;
+		return Err(LedgerError::DuplicateTx(transaction.tx));
 	}
-	match accounts.get_mut(&transaction.client) {
-		Some(account) => account.total += transaction.amount(),
+	match store.get_account(transaction.client) {
+		Some(mut account) => {
+			account.total += transaction.amount();
+			store.upsert_account(transaction.client, account);
+		}
 		None => {
 			log::debug!("New client '{}'", transaction.client);
-			accounts.insert(
+			store.upsert_account(
 				transaction.client,
 				Account::new_deposit(transaction.amount()),
 			);
 		}
 	}
-	tx_cache.insert(
+	store.insert_tx(
 		transaction.tx,
 		CachedTx::new(transaction.amount(), transaction.client),
 	);
+	Ok(())
 }
 
 /// Handle Withdrawal
@@ -90,16 +283,16 @@ fn handle_deposit(accounts: &mut Accounts, tx_cache: &mut TxCache, transaction:
 /// the account if the requested amount is less than the available funds. NB it's implied by the
 /// spec that withdrawals are not to be disputed. Therefore we do not enter this transaction into
 /// the transaction cache.
-fn handle_withdrawal(accounts: &mut Accounts, transaction: Transaction) {
-	match accounts.get_mut(&transaction.client) {
-		Some(account) => {
-			if account.available() >= transaction.amount() {
-				account.total -= transaction.amount()
-			} else {
-				log::debug!("Ignoring withdrawal exceeding available funds");
-			}
-		}
-		None => log::debug!("Ignoring missing client '{}'", transaction.client),
+fn handle_withdrawal<S: Store>(store: &mut S, transaction: &Transaction) -> Result<(), LedgerError> {
+	let mut account = store
+		.get_account(transaction.client)
+		.ok_or(LedgerError::UnknownClient(transaction.client))?;
+	if account.available() >= transaction.amount() {
+		account.total -= transaction.amount();
+		store.upsert_account(transaction.client, account);
+		Ok(())
+	} else {
+		Err(LedgerError::InsufficientFunds(transaction.client))
 	}
 }
 
@@ -108,15 +301,18 @@ fn handle_withdrawal(accounts: &mut Accounts, transaction: Transaction) {
 /// If the transaction or client account doesn't exist, or the transaction is already disputed,
 /// we ignore this request. Otherwise mark the transaction as disputed and the corresponding
 /// funds as `held` in the client account.
-fn handle_dispute(accounts: &mut Accounts, tx_cache: &mut TxCache, transaction: Transaction) {
-	if let Some((account, cached_tx)) = get_existing(accounts, tx_cache, &transaction) {
-		if !cached_tx.disputed {
-			cached_tx.disputed = true;
-			account.held += cached_tx.amount;
-		} else {
-			log::debug!("Ignoring already disputed tx '{}'", transaction.tx);
-		}
-	};
+fn handle_dispute<S: Store>(store: &mut S, transaction: &Transaction) -> Result<(), LedgerError> {
+	let (mut account, mut cached_tx) = get_existing(store, transaction)?;
+	// A dispute is only legal from the initial `Processed` state; a transaction that has already
+	// been disputed (or resolved/charged back via a prior dispute) can never be re-held.
+	if cached_tx.state != TxState::Processed {
+		return Err(LedgerError::AlreadyDisputed(transaction.tx));
+	}
+	cached_tx.state = TxState::Disputed;
+	account.held += cached_tx.amount;
+	store.upsert_account(transaction.client, account);
+	store.insert_tx(transaction.tx, cached_tx);
+	Ok(())
 }
 
 /// Handle Resolve
@@ -124,15 +320,16 @@ fn handle_dispute(accounts: &mut Accounts, tx_cache: &mut TxCache, transaction:
 /// If the transaction or client account doesn't exist, or the transaction is not currently
 /// disputed, we ignore this request. Otherwise mark the transaction as no longer disputed
 /// and release the corresponding funds in the client account.
-fn handle_resolve(accounts: &mut Accounts, tx_cache: &mut TxCache, transaction: Transaction) {
-	if let Some((account, cached_tx)) = get_existing(accounts, tx_cache, &transaction) {
-		if cached_tx.disputed {
-			account.held -= cached_tx.amount;
-			cached_tx.disputed = false;
-		} else {
-			log::debug!("Ignoring resolve on undiputed tx '{}'", transaction.tx);
-		}
+fn handle_resolve<S: Store>(store: &mut S, transaction: &Transaction) -> Result<(), LedgerError> {
+	let (mut account, mut cached_tx) = get_existing(store, transaction)?;
+	if cached_tx.state != TxState::Disputed {
+		return Err(LedgerError::NotDisputed(transaction.tx));
 	}
+	account.held -= cached_tx.amount;
+	cached_tx.state = TxState::Resolved;
+	store.upsert_account(transaction.client, account);
+	store.insert_tx(transaction.tx, cached_tx);
+	Ok(())
 }
 
 /// Handle Chargeback
@@ -140,54 +337,65 @@ fn handle_resolve(accounts: &mut Accounts, tx_cache: &mut TxCache, transaction:
 /// If the transaction or client account doesn't exist, or the transaction is not currently
 /// disputed, we ignore this request. Otherwise mark the transaction as no longer disputed,
 /// remove disputed funds from the client account, and lock the account.
-fn handle_chargeback(accounts: &mut Accounts, tx_cache: &mut TxCache, transaction: Transaction) {
-	if let Some((account, cached_tx)) = get_existing(accounts, tx_cache, &transaction) {
-		if cached_tx.disputed {
-			account.held -= cached_tx.amount;
-			account.total -= cached_tx.amount;
-			account.locked = true;
-			cached_tx.disputed = false;
-		} else {
-			log::debug!("Ignoring chargeback on undiputed tx '{}'", transaction.tx);
-		}
+fn handle_chargeback<S: Store>(store: &mut S, transaction: &Transaction) -> Result<(), LedgerError> {
+	let (mut account, mut cached_tx) = get_existing(store, transaction)?;
+	if cached_tx.state != TxState::Disputed {
+		return Err(LedgerError::NotDisputed(transaction.tx));
 	}
+	account.held -= cached_tx.amount;
+	account.total -= cached_tx.amount;
+	account.locked = true;
+	cached_tx.state = TxState::ChargedBack;
+	store.upsert_account(transaction.client, account);
+	store.insert_tx(transaction.tx, cached_tx);
+	Ok(())
 }
 
 /// Get Existing
 ///
 /// Convenience function to get client account, get cached transaction and check that the client
-/// ID matches the incoming transaction, logging any relevant information if something is awry.
-/// Used in handling disputes, resolves and chargebacks.
-fn get_existing<'a>(
-	accounts: &'a mut Accounts,
-	tx_cache: &'a mut TxCache,
+/// ID matches the incoming transaction, returning a [`LedgerError`] if something is awry. Used in
+/// handling disputes, resolves and chargebacks.
+fn get_existing<S: Store>(
+	store: &S,
 	transaction: &Transaction,
-) -> Option<(&'a mut Account, &'a mut CachedTx)> {
-	if let Some(account) = accounts.get_mut(&transaction.client) {
-		if let Some(cached_tx) = tx_cache.get_mut(&transaction.tx) {
-			if cached_tx.client == transaction.client {
-				return Some((account, cached_tx));
-			} else {
-				log::debug!("Ignoring client mismatch for tx '{}'", transaction.tx);
-			}
-		} else {
-			log::debug!("Ignoring missing tx '{}'", transaction.tx);
-		}
-	} else {
-		log::debug!("Ignoring missing client '{}'", transaction.client);
-	};
-	None
+) -> Result<(Account, CachedTx), LedgerError> {
+	let account = store
+		.get_account(transaction.client)
+		.ok_or(LedgerError::UnknownClient(transaction.client))?;
+	let cached_tx = store
+		.get_tx(transaction.tx)
+		.ok_or(LedgerError::UnknownTx(transaction.tx))?;
+	if cached_tx.client != transaction.client {
+		return Err(LedgerError::ClientMismatch(transaction.tx));
+	}
+	Ok((account, cached_tx))
 }
 
+/// Existential deposit: accounts whose total falls below this are pruned from the output so dead
+/// "dust" accounts (e.g. those driven negative by a chargeback) don't accumulate. Defaults to
+/// zero, which prunes only accounts left with a negative balance.
+pub const EXISTENTIAL_DEPOSIT: Amount = Amount::ZERO;
+
 /// Write Accounts
 ///
-/// Write all account information as a CSV to `wtr` (required trait bound `std::io::Write`).
-fn write_accounts<W: Write>(wtr: &mut W, accounts: Accounts) -> Result<()> {
+/// Write all account information as a CSV to `wtr` (required trait bound `std::io::Write`),
+/// applying the existential-deposit policy: accounts that [`Account::is_dust`] relative to
+/// `existential_deposit` are skipped.
+fn write_accounts<W: Write, S: Store>(
+	wtr: &mut W,
+	store: &S,
+	existential_deposit: Amount,
+) -> Result<()> {
 	let mut buffered = BufWriter::new(wtr);
 	write!(buffered, "client,available,held,total,locked\n")?;
-	for (client, account) in accounts.iter() {
-		write_account(&mut buffered, client, account)?;
-	}
+	let mut result = Ok(());
+	store.for_each_account(|client, account| {
+		if result.is_ok() && !account.is_dust(existential_deposit) {
+			result = write_account(&mut buffered, &client, account);
+		}
+	});
+	result?;
 	buffered.flush()?;
 	Ok(())
 }
@@ -198,7 +406,7 @@ fn write_accounts<W: Write>(wtr: &mut W, accounts: Accounts) -> Result<()> {
 fn write_account<W: Write>(wtr: &mut W, client: &ClientId, account: &Account) -> Result<()> {
 	write!(
 		wtr,
-		"{},{:.4},{:.4},{:.4},{}\n",
+		"{},{},{},{},{}\n",
 		client,
 		account.available(),
 		account.held,
@@ -222,115 +430,182 @@ mod test {
 				r#type: TransactionType::Deposit,
 				client: 1,
 				tx: 1,
-				amount: Some(1.0),
+				amount: Some("1.0".parse().unwrap()),
 			}
 		}
 	}
 
-	#[fixture]
-	fn accounts() -> Accounts {
-		Accounts::new()
+	/// Convenience for asserting against whole-unit balances in tests.
+	fn units(n: i64) -> Amount {
+		Amount::from_raw(n * crate::types::SCALE)
 	}
 
 	#[fixture]
-	fn tx_cache() -> TxCache {
-		TxCache::new()
+	fn store() -> MemStore {
+		MemStore::new()
 	}
 
 	#[rstest]
-	fn handle_deposit_creates_account(mut accounts: Accounts, mut tx_cache: TxCache) {
+	fn handle_deposit_creates_account(mut store: MemStore) {
 		let deposit = Transaction::new();
-		handle_deposit(&mut accounts, &mut tx_cache, deposit);
-		assert_eq!(accounts.len(), 1);
-		assert_eq!(tx_cache.len(), 1);
+		handle_deposit(&mut store, &deposit).unwrap();
+		assert!(store.get_account(1).is_some());
+		assert!(store.get_tx(1).is_some());
 	}
 
 	#[rstest]
-	fn handle_deposit_adds_to_existing_account(mut accounts: Accounts, mut tx_cache: TxCache) {
+	fn handle_deposit_adds_to_existing_account(mut store: MemStore) {
 		let deposit1 = Transaction::new();
 		let mut deposit2 = Transaction::new();
 		deposit2.tx = 2;
-		handle_deposit(&mut accounts, &mut tx_cache, deposit1);
-		handle_deposit(&mut accounts, &mut tx_cache, deposit2);
-		assert_eq!(accounts.len(), 1);
-		assert_eq!(tx_cache.len(), 2);
+		handle_deposit(&mut store, &deposit1).unwrap();
+		handle_deposit(&mut store, &deposit2).unwrap();
+		assert!(store.get_tx(1).is_some());
+		assert!(store.get_tx(2).is_some());
 
-		let total = accounts.get(&1).map_or(-10.0, |x| x.total);
-		assert_eq!(total, 2.0);
+		let total = store.get_account(1).map_or(units(-10), |x| x.total);
+		assert_eq!(total, units(2));
 	}
 
 	#[rstest]
-	fn handle_withdrawal_subtracts_from_account(mut accounts: Accounts, mut tx_cache: TxCache) {
+	fn handle_withdrawal_subtracts_from_account(mut store: MemStore) {
 		let deposit = Transaction::new();
 		let withdrawal = Transaction::new();
-		handle_deposit(&mut accounts, &mut tx_cache, deposit);
-		handle_withdrawal(&mut accounts, withdrawal);
-		assert_eq!(accounts.len(), 1);
-		assert_eq!(tx_cache.len(), 1);
+		handle_deposit(&mut store, &deposit).unwrap();
+		handle_withdrawal(&mut store, &withdrawal).unwrap();
+		assert!(store.get_account(1).is_some());
+		assert!(store.get_tx(1).is_some());
+
+		let total = store.get_account(1).map_or(units(-10), |x| x.total);
+		assert_eq!(total, Amount::ZERO);
 	}
 
 	#[rstest]
-	fn cant_withdraw_more_than_available(mut accounts: Accounts, mut tx_cache: TxCache) {
+	fn cant_withdraw_more_than_available(mut store: MemStore) {
 		let deposit = Transaction::new();
 		let mut withdrawal = Transaction::new();
-		withdrawal.amount = Some(deposit.amount() * 2.0);
-		handle_deposit(&mut accounts, &mut tx_cache, deposit);
-		handle_withdrawal(&mut accounts, withdrawal);
-		let total = accounts.get(&1).map_or(-10.0, |x| x.total);
-		assert_eq!(total, 1.0);
+		withdrawal.amount = Some(deposit.amount() + deposit.amount());
+		handle_deposit(&mut store, &deposit).unwrap();
+		assert_eq!(
+			handle_withdrawal(&mut store, &withdrawal),
+			Err(LedgerError::InsufficientFunds(1))
+		);
+		let total = store.get_account(1).map_or(units(-10), |x| x.total);
+		assert_eq!(total, units(1));
 	}
 
 	#[rstest]
-	fn handle_withdrawal_doesnt_create_unseen_account(mut accounts: Accounts) {
+	fn handle_withdrawal_doesnt_create_unseen_account(mut store: MemStore) {
 		let withdrawal = Transaction::new();
-		handle_withdrawal(&mut accounts, withdrawal);
-		assert_eq!(accounts.len(), 0);
+		assert_eq!(
+			handle_withdrawal(&mut store, &withdrawal),
+			Err(LedgerError::UnknownClient(1))
+		);
+		assert!(store.get_account(1).is_none());
 	}
 
 	#[rstest]
-	fn handle_dispute_marks_funds_correctly(mut accounts: Accounts, mut tx_cache: TxCache) {
+	fn handle_dispute_marks_funds_correctly(mut store: MemStore) {
 		let deposit = Transaction::new();
 		let dispute = Transaction::new();
-		handle_deposit(&mut accounts, &mut tx_cache, deposit);
-		handle_dispute(&mut accounts, &mut tx_cache, dispute);
+		handle_deposit(&mut store, &deposit).unwrap();
+		handle_dispute(&mut store, &dispute).unwrap();
 
-		let held = accounts.get(&1).map_or(-10.0, |x| x.held);
-		assert_eq!(held, 1.0);
+		let held = store.get_account(1).map_or(units(-10), |x| x.held);
+		assert_eq!(held, units(1));
 
-		let disputed = tx_cache.get(&1).map_or(false, |x| x.disputed);
-		assert!(disputed);
+		let state = store.get_tx(1).map(|x| x.state);
+		assert_eq!(state, Some(TxState::Disputed));
 	}
 
 	#[rstest]
-	fn handle_dispute_doesnt_effect_wrong_client(mut accounts: Accounts, mut tx_cache: TxCache) {
+	fn handle_dispute_doesnt_effect_wrong_client(mut store: MemStore) {
 		let deposit = Transaction::new();
 		let mut dispute = Transaction::new();
 		dispute.client = 2;
-		handle_deposit(&mut accounts, &mut tx_cache, deposit);
-		handle_dispute(&mut accounts, &mut tx_cache, dispute);
+		handle_deposit(&mut store, &deposit).unwrap();
+		assert_eq!(
+			handle_dispute(&mut store, &dispute),
+			Err(LedgerError::UnknownClient(2))
+		);
 
-		let held = accounts.get(&1).map_or(-10.0, |x| x.held);
-		assert_eq!(held, 0.0);
+		let held = store.get_account(1).map_or(units(-10), |x| x.held);
+		assert_eq!(held, Amount::ZERO);
 
-		let disputed = tx_cache.get(&1).map_or(true, |x| x.disputed);
-		assert!(!disputed);
+		let state = store.get_tx(1).map(|x| x.state);
+		assert_eq!(state, Some(TxState::Processed));
 	}
 
 	#[rstest]
-	fn total_funds_go_negative(mut accounts: Accounts, mut tx_cache: TxCache) {
+	fn total_funds_go_negative(mut store: MemStore) {
 		let deposit = Transaction::new();
 		let withdrawal = Transaction::new();
 		let dispute = Transaction::new();
 		let chargeback = Transaction::new();
-		handle_deposit(&mut accounts, &mut tx_cache, deposit);
-		handle_withdrawal(&mut accounts, withdrawal);
-		handle_dispute(&mut accounts, &mut tx_cache, dispute);
-		handle_chargeback(&mut accounts, &mut tx_cache, chargeback);
+		handle_deposit(&mut store, &deposit).unwrap();
+		handle_withdrawal(&mut store, &withdrawal).unwrap();
+		handle_dispute(&mut store, &dispute).unwrap();
+		handle_chargeback(&mut store, &chargeback).unwrap();
 
-		let total = accounts.get(&1).map_or(-10.0, |x| x.total);
-		assert_eq!(total, -1.0);
+		let total = store.get_account(1).map_or(units(-10), |x| x.total);
+		assert_eq!(total, units(-1));
 
-		let locked = accounts.get(&1).map_or(false, |x| x.locked);
+		let locked = store.get_account(1).map_or(false, |x| x.locked);
 		assert!(locked);
 	}
+
+	#[rstest]
+	fn reserve_moves_funds_out_of_available(mut store: MemStore) {
+		let deposit = Transaction::new();
+		handle_deposit(&mut store, &deposit).unwrap();
+		let mut account = store.get_account(1).unwrap();
+
+		assert!(account.reserve(units(1)));
+		assert_eq!(account.reserved, units(1));
+		assert_eq!(account.available(), Amount::ZERO);
+	}
+
+	#[rstest]
+	fn cant_reserve_more_than_available(mut store: MemStore) {
+		let deposit = Transaction::new();
+		handle_deposit(&mut store, &deposit).unwrap();
+		let mut account = store.get_account(1).unwrap();
+
+		assert!(!account.reserve(units(2)));
+		assert_eq!(account.reserved, Amount::ZERO);
+	}
+
+	#[rstest]
+	fn unreserve_releases_funds_back_to_available(mut store: MemStore) {
+		let deposit = Transaction::new();
+		handle_deposit(&mut store, &deposit).unwrap();
+		let mut account = store.get_account(1).unwrap();
+
+		account.reserve(units(1));
+		assert!(account.unreserve(units(1)));
+		assert_eq!(account.reserved, Amount::ZERO);
+		assert_eq!(account.available(), units(1));
+
+		// Can't release more than is currently reserved.
+		assert!(!account.unreserve(units(1)));
+	}
+
+	#[rstest]
+	fn negative_accounts_are_pruned_as_dust(mut store: MemStore) {
+		// A chargeback drives the account negative, leaving dead "dust".
+		let deposit = Transaction::new();
+		let withdrawal = Transaction::new();
+		let dispute = Transaction::new();
+		let chargeback = Transaction::new();
+		handle_deposit(&mut store, &deposit).unwrap();
+		handle_withdrawal(&mut store, &withdrawal).unwrap();
+		handle_dispute(&mut store, &dispute).unwrap();
+		handle_chargeback(&mut store, &chargeback).unwrap();
+
+		let mut output = Vec::new();
+		write_accounts(&mut output, &store, EXISTENTIAL_DEPOSIT).unwrap();
+		let output = std::str::from_utf8(&output).unwrap();
+		// Only the header remains; the negative-balance account is pruned.
+		assert_eq!(output, "client,available,held,total,locked\n");
+	}
 }
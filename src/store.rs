@@ -0,0 +1,77 @@
+//! Pluggable storage backends for accounts and cached transactions.
+//!
+//! `process_transactions` is generic over the [`Store`] trait rather than the concrete
+//! `HashMap`s it used to hard-code, so the same engine can drive an in-memory [`MemStore`] for
+//! datasets that fit in RAM or a disk/embedded-KV backend for transaction logs with millions of
+//! distinct clients. Each handler only ever reads and writes whole `Account`/`CachedTx` values,
+//! which lets out-of-core implementations serialise them without handing out references into
+//! their backing store.
+
+use crate::types::{Account, Accounts, CachedTx, ClientId, TransactionId, TxCache};
+
+/// Backend for the engine's account and transaction state.
+///
+/// Accounts and transactions are fetched and stored by value so that implementations backed by a
+/// disk or key-value store can (de)serialise records freely. The in-memory [`MemStore`] simply
+/// clones out of, and inserts back into, its `HashMap`s.
+pub trait Store {
+	/// Fetch a client's current account, if one exists.
+	fn get_account(&self, client: ClientId) -> Option<Account>;
+
+	/// Insert or replace a client's account.
+	fn upsert_account(&mut self, client: ClientId, account: Account);
+
+	/// Fetch a cached transaction, if one exists.
+	fn get_tx(&self, tx: TransactionId) -> Option<CachedTx>;
+
+	/// Insert or replace a cached transaction.
+	fn insert_tx(&mut self, tx: TransactionId, cached: CachedTx);
+
+	/// Visit every account, e.g. to write the final output.
+	fn for_each_account<F: FnMut(ClientId, &Account)>(&self, f: F);
+}
+
+/// The default in-memory store, backing accounts and transactions with `HashMap`s.
+#[derive(Debug, Default)]
+pub struct MemStore {
+	accounts: Accounts,
+	tx_cache: TxCache,
+}
+
+impl MemStore {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Merge another store's accounts into this one.
+	///
+	/// Used to recombine per-shard partitions after parallel processing. Clients are partitioned
+	/// disjointly across shards, so no account is ever seen in more than one partition.
+	pub fn merge(&mut self, other: MemStore) {
+		self.accounts.extend(other.accounts);
+	}
+}
+
+impl Store for MemStore {
+	fn get_account(&self, client: ClientId) -> Option<Account> {
+		self.accounts.get(&client).cloned()
+	}
+
+	fn upsert_account(&mut self, client: ClientId, account: Account) {
+		self.accounts.insert(client, account);
+	}
+
+	fn get_tx(&self, tx: TransactionId) -> Option<CachedTx> {
+		self.tx_cache.get(&tx).cloned()
+	}
+
+	fn insert_tx(&mut self, tx: TransactionId, cached: CachedTx) {
+		self.tx_cache.insert(tx, cached);
+	}
+
+	fn for_each_account<F: FnMut(ClientId, &Account)>(&self, mut f: F) {
+		for (client, account) in self.accounts.iter() {
+			f(*client, account);
+		}
+	}
+}
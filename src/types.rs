@@ -1,13 +1,28 @@
 //! Global type definitions.
 
 use std::collections::HashMap;
+use std::fmt;
+use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+use std::str::FromStr;
 
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+use thiserror::Error;
 
-pub type Amount = f64;
 pub type ClientId = u16;
 pub type TransactionId = u32;
 
+/// Number of integer sub-units used to represent a single currency unit. Amounts are stored as
+/// whole ten-thousandths (4 decimal places), matching the precision the spec requires.
+pub const SCALE: i64 = 10_000;
+
+/// A monetary amount stored as a fixed-point integer (ten-thousandths of a unit).
+///
+/// Using an integer base unit rather than an `f64` means balance arithmetic is exact: deposits
+/// like `2.742` round-trip losslessly and repeated additions/subtractions never accumulate the
+/// rounding drift that made the old `{:.4}` formatting necessary.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Amount(i64);
+
 /// Maps client IDs to their current output state.
 pub type Accounts = HashMap<ClientId, Account>;
 
@@ -24,19 +39,59 @@ pub enum TransactionType {
 	Chargeback,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Account {
 	// NB `available` is an inferred value
 	pub held: Amount,
+	pub reserved: Amount,
 	pub total: Amount,
 	pub locked: bool,
 }
 
-#[derive(Debug)]
+/// Lifecycle state of a cached transaction.
+///
+/// Transactions only ever follow the edges `Processed → Disputed`, `Disputed → Resolved` and
+/// `Disputed → ChargedBack`; `Resolved` and `ChargedBack` are terminal. Modelling this explicitly
+/// (rather than a single `disputed: bool`) prevents a resolved or charged-back transaction from
+/// being re-disputed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxState {
+	Processed,
+	Disputed,
+	Resolved,
+	ChargedBack,
+}
+
+#[derive(Debug, Clone)]
 pub struct CachedTx {
 	pub amount: Amount,
 	pub client: ClientId,
-	pub disputed: bool,
+	pub state: TxState,
+}
+
+/// Reasons a transaction could not be applied.
+///
+/// Handlers return these instead of silently logging so callers can distinguish anomalies that
+/// the spec says to ignore (e.g. a dispute against an unknown transaction) from genuinely
+/// malformed input.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LedgerError {
+	#[error("unknown client '{0}'")]
+	UnknownClient(ClientId),
+	#[error("unknown transaction '{0}'")]
+	UnknownTx(TransactionId),
+	#[error("client mismatch for transaction '{0}'")]
+	ClientMismatch(TransactionId),
+	#[error("duplicate transaction '{0}'")]
+	DuplicateTx(TransactionId),
+	#[error("withdrawal exceeds available funds for client '{0}'")]
+	InsufficientFunds(ClientId),
+	#[error("transaction '{0}' is already disputed")]
+	AlreadyDisputed(TransactionId),
+	#[error("transaction '{0}' is not disputed")]
+	NotDisputed(TransactionId),
+	#[error("account '{0}' is frozen")]
+	FrozenAccount(ClientId),
 }
 
 #[derive(Debug, Deserialize)]
@@ -47,28 +102,69 @@ pub struct Transaction {
 	pub amount: Option<Amount>,
 }
 
+impl Amount {
+	/// The zero amount.
+	pub const ZERO: Amount = Amount(0);
+
+	/// Construct an amount directly from its raw ten-thousandths representation.
+	pub const fn from_raw(raw: i64) -> Self {
+		Amount(raw)
+	}
+
+	/// The underlying ten-thousandths value.
+	pub const fn raw(self) -> i64 {
+		self.0
+	}
+}
+
 impl Account {
 	pub fn new_deposit(amount: Amount) -> Self {
 		Self {
-			held: 0.0,
+			held: Amount::ZERO,
+			reserved: Amount::ZERO,
 			total: amount,
 			locked: false,
 		}
 	}
 
+	/// Funds that are neither held (by a dispute) nor reserved, and so can be freely spent.
 	pub fn available(&self) -> Amount {
-		self.total - self.held
+		self.total - self.held - self.reserved
+	}
+
+	/// Reserve `amount` of the account's free funds, independently of any dispute. Returns `false`
+	/// (leaving the account untouched) if there are insufficient available funds to cover it.
+	pub fn reserve(&mut self, amount: Amount) -> bool {
+		if self.available() < amount {
+			return false;
+		}
+		self.reserved += amount;
+		true
+	}
+
+	/// Release a previously reserved `amount` back to free funds. Reserved funds are only released
+	/// once the reservation is lifted, so this fails (returning `false`) if more than the reserved
+	/// balance is requested.
+	pub fn unreserve(&mut self, amount: Amount) -> bool {
+		if self.reserved < amount {
+			return false;
+		}
+		self.reserved -= amount;
+		true
+	}
+
+	/// Whether the account's total has fallen below the existential-deposit `threshold`, e.g. after
+	/// a chargeback drove it negative or left only dust.
+	pub fn is_dust(&self, threshold: Amount) -> bool {
+		self.total < threshold
 	}
 }
 
 impl Transaction {
-	/// For simplicty, we return a default amount of 0.0 if amount is missing, thereby avoiding
+	/// For simplicty, we return a default amount of zero if amount is missing, thereby avoiding
 	/// handling `Option`s in various handlers.
 	pub fn amount(&self) -> Amount {
-		match self.amount {
-			Some(x) => x,
-			None => 0.0,
-		}
+		self.amount.unwrap_or(Amount::ZERO)
 	}
 }
 
@@ -77,7 +173,118 @@ impl CachedTx {
 		Self {
 			amount,
 			client,
-			disputed: false,
+			state: TxState::Processed,
 		}
 	}
 }
+
+/// Error returned when a CSV `amount` field cannot be parsed as a fixed-point [`Amount`].
+#[derive(Debug)]
+pub struct ParseAmountError(String);
+
+impl fmt::Display for ParseAmountError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "invalid amount '{}'", self.0)
+	}
+}
+
+impl std::error::Error for ParseAmountError {}
+
+impl FromStr for Amount {
+	type Err = ParseAmountError;
+
+	/// Parse an amount by splitting on `.`, validating at most 4 fractional digits and scaling the
+	/// result into whole ten-thousandths.
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let trimmed = s.trim();
+		let err = || ParseAmountError(s.to_string());
+
+		let (negative, digits) = match trimmed.strip_prefix('-') {
+			Some(rest) => (true, rest),
+			None => (false, trimmed),
+		};
+
+		let (int_part, frac_part) = match digits.split_once('.') {
+			Some((i, f)) => (i, f),
+			None => (digits, ""),
+		};
+		if frac_part.len() > 4 || !frac_part.bytes().all(|b| b.is_ascii_digit()) {
+			return Err(err());
+		}
+
+		let int: i64 = if int_part.is_empty() {
+			0
+		} else {
+			int_part.parse().map_err(|_| err())?
+		};
+		let frac: i64 = if frac_part.is_empty() {
+			0
+		} else {
+			// Left-justify to exactly 4 digits so e.g. `.5` scales to 5000, not 5.
+			format!("{:0<4}", frac_part).parse().map_err(|_| err())?
+		};
+
+		let magnitude = int
+			.checked_mul(SCALE)
+			.and_then(|v| v.checked_add(frac))
+			.ok_or_else(err)?;
+		Ok(Amount(if negative { -magnitude } else { magnitude }))
+	}
+}
+
+impl<'de> Deserialize<'de> for Amount {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		let raw = String::deserialize(deserializer)?;
+		raw.parse().map_err(serde::de::Error::custom)
+	}
+}
+
+impl fmt::Display for Amount {
+	/// Re-insert the decimal point with exactly 4 fractional places.
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let magnitude = self.0.abs();
+		if self.0 < 0 {
+			write!(f, "-")?;
+		}
+		write!(f, "{}.{:04}", magnitude / SCALE, magnitude % SCALE)
+	}
+}
+
+impl Add for Amount {
+	type Output = Amount;
+
+	fn add(self, rhs: Amount) -> Amount {
+		Amount(self.0 + rhs.0)
+	}
+}
+
+impl Sub for Amount {
+	type Output = Amount;
+
+	fn sub(self, rhs: Amount) -> Amount {
+		Amount(self.0 - rhs.0)
+	}
+}
+
+impl Neg for Amount {
+	type Output = Amount;
+
+	fn neg(self) -> Amount {
+		Amount(-self.0)
+	}
+}
+
+impl AddAssign for Amount {
+	fn add_assign(&mut self, rhs: Amount) {
+		self.0 += rhs.0;
+	}
+}
+
+impl SubAssign for Amount {
+	fn sub_assign(&mut self, rhs: Amount) {
+		self.0 -= rhs.0;
+	}
+}